@@ -1,14 +1,69 @@
 use chrono::Local;
 use clap::{Parser, Subcommand};
-use rusqlite::{params, Connection};
+use rusqlite::{params, types::Value, Connection};
 use serde::{Deserialize, Serialize};
-use std::{fs::File, io::Write, path::Path};
+use std::{
+    fmt,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+enum AppError {
+    Db(rusqlite::Error),
+    Io(std::io::Error),
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    Validation(String),
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Db(e) => write!(f, "database error: {}", e),
+            AppError::Io(e) => write!(f, "I/O error: {}", e),
+            AppError::Csv(e) => write!(f, "CSV error: {}", e),
+            AppError::Json(e) => write!(f, "JSON error: {}", e),
+            AppError::Validation(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<rusqlite::Error> for AppError {
+    fn from(e: rusqlite::Error) -> Self {
+        AppError::Db(e)
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        AppError::Io(e)
+    }
+}
+
+impl From<csv::Error> for AppError {
+    fn from(e: csv::Error) -> Self {
+        AppError::Csv(e)
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        AppError::Json(e)
+    }
+}
 
 /// Tim Hortons Expense Tracker CLI
 #[derive(Parser)]
 #[command(name = "Tim Hortons Tracker")]
 #[command(about = "Track your daily Tim Hortons expenses", long_about = None)]
 struct Cli {
+    /// Path to the SQLite database file (falls back to $TRACKER_DB, then the user data dir)
+    #[arg(long, global = true)]
+    db: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -27,8 +82,27 @@ enum Commands {
     DailyTotal {
         date: Option<String>,
     },
-    /// Export all orders to a CSV file
-    Export { filepath: String },
+    /// Export all orders to a file
+    Export {
+        filepath: String,
+        /// Output format: csv, json, or tsv
+        #[arg(long, default_value = "csv")]
+        format: String,
+    },
+    /// Run a raw read-only SQL query against the orders table
+    Query {
+        sql: String,
+        #[arg(long)]
+        tsv: bool,
+    },
+    /// Import orders from a CSV file
+    Import { filepath: String },
+    /// View total expenses over a date range, optionally broken down by day or item
+    RangeTotal {
+        start: String,
+        end: String,
+        group_by: Option<String>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -46,8 +120,16 @@ impl Order {
 }
 
 fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), AppError> {
     let cli = Cli::parse();
-    let conn = init_db();
+    let db_path = resolve_db_path(cli.db.as_deref());
+    let mut conn = init_db(&db_path)?;
 
     match &cli.command {
         Commands::Add {
@@ -59,25 +141,74 @@ fn main() {
             let today = Local::now().format("%Y-%m-%d").to_string();
             let order_date = date.clone().unwrap_or(today);
 
-            add_order(&conn, item, *quantity, *price, &order_date);
-            println!("Order added: {} x{} @ ${:.2} on {}", item, quantity, price, order_date);
+            add_order(&conn, item, *quantity, *price, &order_date)?;
+            let order = Order {
+                item_name: item.clone(),
+                quantity: *quantity,
+                price: *price,
+                date: order_date.clone(),
+            };
+            println!(
+                "Order added: {} x{} @ ${:.2} on {} (total ${:.2})",
+                item,
+                quantity,
+                price,
+                order_date,
+                order.total_cost()
+            );
         }
         Commands::DailyTotal { date } => {
             let today = Local::now().format("%Y-%m-%d").to_string();
             let query_date = date.clone().unwrap_or(today);
 
-            let total = calculate_daily_total(&conn, &query_date);
+            let total = calculate_daily_total(&conn, &query_date)?;
             println!("Total for {}: ${:.2}", query_date, total);
         }
-        Commands::Export { filepath } => {
-            export_to_csv(&conn, filepath);
+        Commands::Export { filepath, format } => {
+            export_orders(&conn, filepath, format)?;
             println!("Orders exported to {}", filepath);
         }
+        Commands::Query { sql, tsv } => {
+            run_query(&conn, sql, *tsv)?;
+        }
+        Commands::Import { filepath } => {
+            let (imported, skipped) = import_from_csv(&mut conn, filepath)?;
+            println!(
+                "Imported {} orders from {} ({} skipped)",
+                imported, filepath, skipped.len()
+            );
+            if !skipped.is_empty() {
+                println!("Skipped lines: {:?}", skipped);
+            }
+        }
+        Commands::RangeTotal { start, end, group_by } => {
+            calculate_range_total(&conn, start, end, group_by.as_deref())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn resolve_db_path(cli_db: Option<&str>) -> PathBuf {
+    if let Some(path) = cli_db {
+        return PathBuf::from(path);
+    }
+    if let Ok(path) = std::env::var("TRACKER_DB") {
+        return PathBuf::from(path);
     }
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("coffee-expense-tracker")
+        .join("timhortons_tracker.db")
 }
 
-fn init_db() -> Connection {
-    let conn = Connection::open("timhortons_tracker.db").expect("Failed to connect to database.");
+fn init_db(path: &Path) -> Result<Connection, AppError> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+    let conn = Connection::open(path)?;
     conn.execute(
         "CREATE TABLE IF NOT EXISTS orders (
             id INTEGER PRIMARY KEY,
@@ -87,51 +218,239 @@ fn init_db() -> Connection {
             date TEXT NOT NULL
         )",
         [],
-    )
-    .expect("Failed to create table.");
-    conn
+    )?;
+    Ok(conn)
 }
 
-fn add_order(conn: &Connection, item: &str, quantity: u32, price: f64, date: &str) {
+fn add_order(conn: &Connection, item: &str, quantity: u32, price: f64, date: &str) -> Result<(), AppError> {
     conn.execute(
         "INSERT INTO orders (item_name, quantity, price, date) VALUES (?1, ?2, ?3, ?4)",
         params![item, quantity, price, date],
-    )
-    .expect("Failed to add order.");
+    )?;
+    Ok(())
+}
+
+fn calculate_daily_total(conn: &Connection, date: &str) -> Result<f64, AppError> {
+    let mut stmt = conn.prepare("SELECT SUM(quantity * price) FROM orders WHERE date = ?1")?;
+    let total: f64 = stmt.query_row(params![date], |row| row.get(0)).unwrap_or(0.0);
+    Ok(total)
 }
 
-fn calculate_daily_total(conn: &Connection, date: &str) -> f64 {
-    let mut stmt = conn
-        .prepare("SELECT SUM(quantity * price) FROM orders WHERE date = ?1")
-        .expect("Failed to prepare statement.");
+fn calculate_range_total(
+    conn: &Connection,
+    start: &str,
+    end: &str,
+    group_by: Option<&str>,
+) -> Result<(), AppError> {
+    match group_by {
+        Some("day") => {
+            let mut stmt = conn.prepare(
+                "SELECT date, SUM(quantity * price) FROM orders \
+                 WHERE date BETWEEN ?1 AND ?2 GROUP BY date ORDER BY date",
+            )?;
+            print_range_breakdown(&mut stmt, start, end)?;
+        }
+        Some("item") => {
+            let mut stmt = conn.prepare(
+                "SELECT item_name, SUM(quantity * price) FROM orders \
+                 WHERE date BETWEEN ?1 AND ?2 GROUP BY item_name ORDER BY item_name",
+            )?;
+            print_range_breakdown(&mut stmt, start, end)?;
+        }
+        Some(other) => {
+            return Err(AppError::Validation(format!(
+                "Unknown group_by value: {} (expected \"day\" or \"item\")",
+                other
+            )));
+        }
+        None => {}
+    }
+
+    let mut stmt = conn.prepare("SELECT SUM(quantity * price) FROM orders WHERE date BETWEEN ?1 AND ?2")?;
     let total: f64 = stmt
-        .query_row(params![date], |row| row.get(0))
+        .query_row(params![start, end], |row| row.get(0))
         .unwrap_or(0.0);
-    total
-}
-
-fn export_to_csv(conn: &Connection, filepath: &str) {
-    let mut stmt = conn
-        .prepare("SELECT item_name, quantity, price, date FROM orders")
-        .expect("Failed to prepare statement.");
-    let orders = stmt
-        .query_map([], |row| {
-            Ok(Order {
-                item_name: row.get(0)?,
-                quantity: row.get(1)?,
-                price: row.get(2)?,
-                date: row.get(3)?,
-            })
+    println!("Total from {} to {}: ${:.2}", start, end, total);
+    Ok(())
+}
+
+fn print_range_breakdown(stmt: &mut rusqlite::Statement, start: &str, end: &str) -> Result<(), AppError> {
+    let rows = stmt.query_map(params![start, end], |row| {
+        let key: String = row.get(0)?;
+        let subtotal: f64 = row.get(1)?;
+        Ok((key, subtotal))
+    })?;
+
+    for row in rows {
+        let (key, subtotal) = row?;
+        println!("{}: ${:.2}", key, subtotal);
+    }
+    Ok(())
+}
+
+fn export_orders(conn: &Connection, filepath: &str, format: &str) -> Result<(), AppError> {
+    let orders = query_all_orders(conn)?;
+
+    match format {
+        "csv" => export_to_csv(&orders, filepath),
+        "tsv" => export_to_tsv(&orders, filepath),
+        "json" => export_to_json(&orders, filepath),
+        other => Err(AppError::Validation(format!(
+            "Unknown export format: {} (expected csv, json, or tsv)",
+            other
+        ))),
+    }
+}
+
+fn query_all_orders(conn: &Connection) -> Result<Vec<Order>, AppError> {
+    let mut stmt = conn.prepare("SELECT item_name, quantity, price, date FROM orders")?;
+    let orders = stmt.query_map([], |row| {
+        Ok(Order {
+            item_name: row.get(0)?,
+            quantity: row.get(1)?,
+            price: row.get(2)?,
+            date: row.get(3)?,
         })
-        .expect("Failed to query orders.");
+    })?;
 
+    let mut result = Vec::new();
+    for order in orders {
+        result.push(order?);
+    }
+    Ok(result)
+}
+
+fn export_to_csv(orders: &[Order], filepath: &str) -> Result<(), AppError> {
     let path = Path::new(filepath);
-    let mut file = File::create(&path).expect("Failed to create file.");
+    let mut file = File::create(path)?;
     let mut wtr = csv::Writer::from_writer(&mut file);
 
     for order in orders {
-        wtr.serialize(order.expect("Failed to serialize order."))
-            .expect("Failed to write to CSV.");
+        wtr.serialize(order)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+fn export_to_tsv(orders: &[Order], filepath: &str) -> Result<(), AppError> {
+    let path = Path::new(filepath);
+    let file = File::create(path)?;
+    let mut wtr = csv::WriterBuilder::new().delimiter(b'\t').from_writer(file);
+
+    for order in orders {
+        wtr.serialize(order)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+fn export_to_json(orders: &[Order], filepath: &str) -> Result<(), AppError> {
+    let path = Path::new(filepath);
+    let file = File::create(path)?;
+    serde_json::to_writer_pretty(file, orders)?;
+    Ok(())
+}
+
+fn run_query(conn: &Connection, sql: &str, tsv: bool) -> Result<(), AppError> {
+    let trimmed = sql.trim();
+    if !trimmed.to_uppercase().starts_with("SELECT") {
+        return Err(AppError::Validation("Only SELECT statements are allowed.".to_string()));
+    }
+
+    let mut stmt = conn.prepare(trimmed)?;
+    let column_count = stmt.column_count();
+    let column_names: Vec<String> = (0..column_count)
+        .map(|i| stmt.column_name(i).map(|n| n.to_string()))
+        .collect::<Result<_, _>>()?;
+
+    let mut rows = stmt.query([])?;
+    let mut row_values: Vec<Vec<String>> = Vec::new();
+    while let Some(row) = rows.next()? {
+        let values: Vec<String> = (0..column_count)
+            .map(|i| -> Result<String, AppError> {
+                let value: Value = row.get(i)?;
+                Ok(format_value(&value))
+            })
+            .collect::<Result<_, _>>()?;
+        row_values.push(values);
     }
-    wtr.flush().expect("Failed to flush CSV writer.");
+
+    if tsv {
+        println!("{}", column_names.join("\t"));
+        for values in &row_values {
+            println!("{}", values.join("\t"));
+        }
+    } else {
+        let mut widths: Vec<usize> = column_names.iter().map(|name| name.len()).collect();
+        for values in &row_values {
+            for (width, value) in widths.iter_mut().zip(values) {
+                *width = (*width).max(value.len());
+            }
+        }
+
+        println!("{}", format_aligned_row(&column_names, &widths));
+        for values in &row_values {
+            println!("{}", format_aligned_row(values, &widths));
+        }
+    }
+
+    Ok(())
+}
+
+fn format_aligned_row(values: &[String], widths: &[usize]) -> String {
+    values
+        .iter()
+        .zip(widths)
+        .map(|(value, width)| format!("{:<width$}", value, width = width))
+        .collect::<Vec<String>>()
+        .join(" | ")
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Integer(i) => i.to_string(),
+        Value::Real(f) => f.to_string(),
+        Value::Text(s) => s.clone(),
+        Value::Blob(_) => "<blob>".to_string(),
+    }
+}
+
+fn is_valid_date(date: &str) -> bool {
+    chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok()
+}
+
+fn import_from_csv(conn: &mut Connection, filepath: &str) -> Result<(usize, Vec<usize>), AppError> {
+    let mut reader = csv::Reader::from_path(filepath)?;
+
+    let mut imported = 0;
+    let mut skipped = Vec::new();
+
+    let tx = conn.transaction()?;
+    {
+        let mut stmt =
+            tx.prepare("INSERT INTO orders (item_name, quantity, price, date) VALUES (?1, ?2, ?3, ?4)")?;
+
+        for (i, result) in reader.deserialize::<Order>().enumerate() {
+            let line = i + 2; // account for the header row
+            let order = match result {
+                Ok(order) => order,
+                Err(_) => {
+                    skipped.push(line);
+                    continue;
+                }
+            };
+
+            if order.quantity == 0 || order.price < 0.0 || !is_valid_date(&order.date) {
+                skipped.push(line);
+                continue;
+            }
+
+            stmt.execute(params![order.item_name, order.quantity, order.price, order.date])?;
+            imported += 1;
+        }
+    }
+    tx.commit()?;
+
+    Ok((imported, skipped))
 }